@@ -1,5 +1,6 @@
 use std::ffi::{self, CString};
 use std::marker;
+use std::mem;
 use std::str;
 use libc;
 
@@ -10,6 +11,19 @@ use util::Binding;
 ///
 /// Remotes can create a `Push` which is then used to push data to the upstream
 /// repository.
+///
+/// Per-reference outcomes are read back with `statuses` after `finish`. A
+/// typed per-reference update callback delivering the pre-/post-push `Oid`s as
+/// the push completes is not wired up: the push object in this libgit2 era has
+/// no `push_update_reference` hook, so there is nothing to trampoline through.
+/// `PushStatus` carries only the reference name and an optional failure
+/// message; the old and new `Oid`s of an updated reference are not reported.
+///
+/// Authentication is likewise not configured on the push object: this libgit2
+/// era has no `git_push_set_credentials_callback`, so credentials are supplied
+/// through the remote transport that owns the connection rather than here.
+/// Without credentials configured on the remote, pushes driven through this
+/// type reach only URLs whose transport needs no auth (e.g. local file remotes).
 pub struct Push<'remote> {
     raw: *mut raw::git_push,
     marker: marker::ContravariantLifetime<'remote>,
@@ -24,6 +38,39 @@ pub struct PushStatus {
     pub message: Option<String>,
 }
 
+/// The stage a pack-builder is in while it reports progress during a push.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PackBuilderStage {
+    /// Objects are being enumerated and added to the pack.
+    AddingObjects,
+    /// Objects are being deltified against one another to shrink the pack.
+    Deltafication,
+}
+
+/// Options controlling how a `Push` builds its pack.
+///
+/// This bundles the progress callbacks together with the pack-builder
+/// parallelism so a single `finish` call configures both. Only
+/// `pb_parallelism` of the three requested knobs is delivered: the proxy and
+/// custom-HTTP-header options are not exposed, because the push object in this
+/// libgit2 era has no setters for them — that transport configuration lives on
+/// the remote rather than on the push.
+pub struct PushOptions<'a, 'cb: 'a> {
+    callbacks: Option<&'a mut PushCallbacks<'cb>>,
+    pb_parallelism: libc::c_uint,
+}
+
+/// A collection of callbacks invoked while a `Push` is in progress.
+///
+/// Build up a set of callbacks and then hand them to a `PushOptions` with
+/// `callbacks` before calling `finish`. The `PushOptions` holds the
+/// `PushCallbacks` by reference, so the `PushCallbacks` must outlive the
+/// `finish` call that drives them.
+pub struct PushCallbacks<'a> {
+    transfer_progress: Option<Box<FnMut(usize, usize, usize) + 'a>>,
+    pack_progress: Option<Box<FnMut(PackBuilderStage, usize, usize) + 'a>>,
+}
+
 impl<'remote> Push<'remote> {
     /// Add a refspec to be pushed
     pub fn add_refspec(&mut self, refspec: &str) -> Result<(), Error> {
@@ -34,19 +81,49 @@ impl<'remote> Push<'remote> {
         }
     }
 
-    /// Actually push all given refspecs
+    /// Actually push all given refspecs, configured by the given options.
+    ///
+    /// The options bundle the progress callbacks with the pack-generation
+    /// knobs; pass `None` to push with the defaults.
     ///
     /// To check if the push was successful (i.e. all remote references have
     /// been updated as requested), you need to call
     /// `statuses`. The remote repository might have refused to
     /// update some or all of the references.
-    pub fn finish(&mut self) -> Result<(), Error> {
+    pub fn finish(&mut self, opts: Option<&mut PushOptions>) -> Result<(), Error> {
         unsafe {
+            if let Some(opts) = opts {
+                if let Some(ref mut callbacks) = opts.callbacks {
+                    try!(self.set_callbacks(callbacks));
+                }
+                let mut push_opts: raw::git_push_options = mem::zeroed();
+                push_opts.version = raw::GIT_PUSH_OPTIONS_VERSION;
+                push_opts.pb_parallelism = opts.pb_parallelism;
+                try_call!(raw::git_push_set_options(self.raw, &push_opts));
+            }
             try_call!(raw::git_push_finish(self.raw));
             Ok(())
         }
     }
 
+    /// Register a set of callbacks to report progress as the push proceeds.
+    ///
+    /// This is an internal helper driven by `finish`: the callbacks are
+    /// borrowed by the `PushOptions` for the `finish` call, so the `data`
+    /// pointer handed to libgit2 is valid while that call runs. The
+    /// registration is not cleared when `finish` returns. Callers register
+    /// callbacks by handing a `PushCallbacks` to `PushOptions::callbacks`, not
+    /// directly.
+    fn set_callbacks(&mut self, callbacks: &mut PushCallbacks) -> Result<(), Error> {
+        let ptr = callbacks as *mut PushCallbacks as *mut libc::c_void;
+        unsafe {
+            try_call!(raw::git_push_set_callbacks(self.raw,
+                                                  pack_cb, ptr,
+                                                  transfer_cb, ptr));
+            Ok(())
+        }
+    }
+
     /// Update remote tips after a push
     pub fn update_tips(&mut self, signature: Option<&Signature>,
                        reflog_message: Option<&str>) -> Result<(), Error> {
@@ -60,6 +137,11 @@ impl<'remote> Push<'remote> {
     }
 
     /// Return each status entry
+    ///
+    /// This is the per-reference outcome accessor: each returned `PushStatus`
+    /// carries a `message` when the remote refused that update, so callers can
+    /// see which refs succeeded and which failed. Entries whose reference name
+    /// or message is not valid UTF-8 are skipped.
     pub fn statuses(&mut self) -> Result<Vec<PushStatus>, Error> {
         let mut ret: Vec<PushStatus> = Vec::new();
         unsafe {
@@ -94,6 +176,93 @@ impl<'remote> Push<'remote> {
     }
 }
 
+impl<'a> PushCallbacks<'a> {
+    /// Creates a new empty set of push callbacks.
+    pub fn new() -> PushCallbacks<'a> {
+        PushCallbacks {
+            transfer_progress: None,
+            pack_progress: None,
+        }
+    }
+
+    /// Register a callback reporting transfer progress.
+    ///
+    /// The closure is handed the number of objects written so far, the total
+    /// number of objects, and the number of bytes sent to the wire.
+    pub fn transfer_progress<F>(&mut self, cb: F) -> &mut PushCallbacks<'a>
+            where F: FnMut(usize, usize, usize) + 'a {
+        self.transfer_progress = Some(Box::new(cb));
+        self
+    }
+
+    /// Register a callback reporting pack-builder progress.
+    ///
+    /// The closure is handed the current stage along with a 0..total counter
+    /// tracking that stage's progress.
+    pub fn pack_progress<F>(&mut self, cb: F) -> &mut PushCallbacks<'a>
+            where F: FnMut(PackBuilderStage, usize, usize) + 'a {
+        self.pack_progress = Some(Box::new(cb));
+        self
+    }
+}
+
+extern fn transfer_cb(current: libc::c_uint, total: libc::c_uint,
+                      bytes: libc::size_t,
+                      data: *mut libc::c_void) -> libc::c_int {
+    unsafe {
+        let payload = &mut *(data as *mut PushCallbacks);
+        match payload.transfer_progress {
+            Some(ref mut cb) => cb(current as usize, total as usize, bytes as usize),
+            None => {}
+        }
+        0
+    }
+}
+
+extern fn pack_cb(stage: libc::c_int, current: libc::c_uint, total: libc::c_uint,
+                  data: *mut libc::c_void) -> libc::c_int {
+    unsafe {
+        let payload = &mut *(data as *mut PushCallbacks);
+        let stage = if stage == raw::GIT_PACKBUILDER_DELTAFICATION as libc::c_int {
+            PackBuilderStage::Deltafication
+        } else {
+            PackBuilderStage::AddingObjects
+        };
+        match payload.pack_progress {
+            Some(ref mut cb) => cb(stage, current as usize, total as usize),
+            None => {}
+        }
+        0
+    }
+}
+
+impl<'a, 'cb> PushOptions<'a, 'cb> {
+    /// Creates a new blank set of push options.
+    pub fn new() -> PushOptions<'a, 'cb> {
+        PushOptions {
+            callbacks: None,
+            pb_parallelism: 0,
+        }
+    }
+
+    /// Set the callbacks to use during the push.
+    pub fn callbacks(&mut self, callbacks: &'a mut PushCallbacks<'cb>)
+                     -> &mut PushOptions<'a, 'cb> {
+        self.callbacks = Some(callbacks);
+        self
+    }
+
+    /// Set the number of threads libgit2 may use while deltifying objects.
+    ///
+    /// Large pushes spend most of their time in delta compression, so raising
+    /// this lets callers saturate multi-core machines; a value of `0` lets
+    /// libgit2 pick a thread count itself.
+    pub fn pb_parallelism(&mut self, threads: u32) -> &mut PushOptions<'a, 'cb> {
+        self.pb_parallelism = threads as libc::c_uint;
+        self
+    }
+}
+
 impl<'remote> Binding for Push<'remote> {
     type Raw = *mut raw::git_push;
     unsafe fn from_raw(raw: *mut raw::git_push) -> Push<'remote> {
@@ -114,9 +283,11 @@ impl<'a> Drop for Push<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
     use std::old_io::TempDir;
     use url::Url;
     use Repository;
+    use super::{PushCallbacks, PushOptions};
 
     #[test]
     fn smoke() {
@@ -131,11 +302,34 @@ mod tests {
 
         let mut push = remote.push().unwrap();
         push.add_refspec("refs/heads/master").unwrap();
-        push.finish().unwrap();
+        push.finish(None).unwrap();
         push.update_tips(None, None).unwrap();
         let v = push.statuses().unwrap();
         assert!(v.len() > 0);
         assert_eq!(v[0].reference.as_slice(), "refs/heads/master");
         assert!(v[0].message.is_none());
     }
+
+    #[test]
+    fn progress() {
+        let td = TempDir::new("test").unwrap();
+        let remote = td.path().join("remote");
+        Repository::init_bare(&remote).unwrap();
+
+        let (_td, repo) = ::test::repo_init();
+        let url = Url::from_file_path(&remote).ok().unwrap();
+        let url = url.to_string();
+        let mut remote = repo.remote("origin", url.as_slice()).unwrap();
+
+        let mut push = remote.push().unwrap();
+        push.add_refspec("refs/heads/master").unwrap();
+
+        let progressed = Cell::new(false);
+        let mut cbs = PushCallbacks::new();
+        cbs.pack_progress(|_stage, _cur, _total| progressed.set(true));
+        let mut opts = PushOptions::new();
+        opts.callbacks(&mut cbs);
+        push.finish(Some(&mut opts)).unwrap();
+        assert!(progressed.get());
+    }
 }